@@ -0,0 +1,58 @@
+//! Reloadable configuration mapping GitHub repositories to their ansible deploy settings.
+
+use camino::Utf8Path;
+use serde::Deserialize;
+
+fn default_inventory() -> String {
+    "inventories/from_controller.yaml".to_string()
+}
+
+fn default_remote_user() -> String {
+    "controller".to_string()
+}
+
+/// Per-repository deployment settings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepoConfig {
+    /// GitHub repository full name, e.g. `dvrpc/crash-api`.
+    pub full_name: String,
+    /// Tag passed to `ansible-playbook --tags` to deploy this app.
+    pub tag: String,
+    #[serde(default = "default_inventory")]
+    pub inventory: String,
+    #[serde(default = "default_remote_user")]
+    pub remote_user: String,
+    /// Extra arguments appended to the `ansible-playbook` invocation for this repo.
+    #[serde(default)]
+    pub extra_args: Vec<String>,
+    /// Overrides the global `receivers` list for this repo's deployment emails, if set.
+    #[serde(default)]
+    pub receivers: Option<Vec<String>>,
+    /// Secret(s) used to verify this repo's webhook deliveries (`X-Hub-Signature-256`). More
+    /// than one is accepted at once so a secret can be rotated without dropping webhooks sent
+    /// with the old one: the newest is added here, the old one removed once GitHub's been
+    /// updated to match.
+    pub webhook_secrets: Vec<String>,
+}
+
+/// Top-level configuration, reloadable from disk without restarting the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub repos: Vec<RepoConfig>,
+    /// Default email receivers for deployment results, used unless a `RepoConfig` overrides it.
+    pub receivers: Vec<String>,
+}
+
+impl Config {
+    /// Reads and parses the config file at `path`.
+    pub fn load(path: &Utf8Path) -> Result<Config, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("unable to read config file {path}: {e}"))?;
+        toml::from_str(&contents).map_err(|e| format!("unable to parse config file {path}: {e}"))
+    }
+
+    /// Looks up the settings for a repository by its GitHub full name.
+    pub fn repo(&self, full_name: &str) -> Option<&RepoConfig> {
+        self.repos.iter().find(|r| r.full_name == full_name)
+    }
+}
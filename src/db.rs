@@ -0,0 +1,156 @@
+//! Sqlite-backed persistence for deployment history.
+
+use std::sync::Mutex;
+
+use camino::Utf8Path;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// Lifecycle state of a single deployment attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Pending,
+    Running,
+    Success,
+    Failure,
+}
+
+impl JobState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JobState::Pending => "pending",
+            JobState::Running => "running",
+            JobState::Success => "success",
+            JobState::Failure => "failure",
+        }
+    }
+
+    fn parse_state(s: &str) -> JobState {
+        match s {
+            "pending" => JobState::Pending,
+            "running" => JobState::Running,
+            "success" => JobState::Success,
+            "failure" => JobState::Failure,
+            other => panic!("unrecognized job state stored in database: {other}"),
+        }
+    }
+}
+
+/// A single recorded deployment attempt, as stored in the `deployments` table.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Deployment {
+    pub id: i64,
+    pub repo: String,
+    pub tag: String,
+    pub sha: String,
+    pub state: JobState,
+    pub started_at: String,
+    pub finished_at: Option<String>,
+    pub stdout: Option<String>,
+    pub stderr: Option<String>,
+}
+
+fn row_to_deployment(row: &Row) -> rusqlite::Result<Deployment> {
+    Ok(Deployment {
+        id: row.get("id")?,
+        repo: row.get("repo")?,
+        tag: row.get("tag")?,
+        sha: row.get("sha")?,
+        state: JobState::parse_state(&row.get::<_, String>("state")?),
+        started_at: row.get("started_at")?,
+        finished_at: row.get("finished_at")?,
+        stdout: row.get("stdout")?,
+        stderr: row.get("stderr")?,
+    })
+}
+
+/// Wraps the sqlite connection used to record deployment history.
+///
+/// `rusqlite::Connection` isn't `Sync`, so access from the HTTP handlers and the background
+/// deployment worker task is serialized behind a `Mutex`.
+pub struct DbCtx {
+    conn: Mutex<Connection>,
+}
+
+impl DbCtx {
+    /// Opens (creating if absent) the sqlite database at `path` and ensures the schema exists.
+    pub fn new(path: &Utf8Path) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS deployments (
+                id          INTEGER PRIMARY KEY AUTOINCREMENT,
+                repo        TEXT NOT NULL,
+                tag         TEXT NOT NULL,
+                sha         TEXT NOT NULL,
+                state       TEXT NOT NULL,
+                started_at  TEXT NOT NULL,
+                finished_at TEXT,
+                stdout      TEXT,
+                stderr      TEXT
+            );",
+        )?;
+        Ok(DbCtx {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Inserts a `pending` row for a deployment that's about to be attempted, returning its id.
+    pub fn insert_pending(&self, repo: &str, tag: &str, sha: &str) -> rusqlite::Result<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO deployments (repo, tag, sha, state, started_at)
+             VALUES (?1, ?2, ?3, ?4, datetime('now'))",
+            params![repo, tag, sha, JobState::Pending.as_str()],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Marks a deployment as `running`, once the worker has taken it off the queue.
+    pub fn mark_running(&self, id: i64) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE deployments SET state = ?1 WHERE id = ?2",
+            params![JobState::Running.as_str(), id],
+        )?;
+        Ok(())
+    }
+
+    /// Records the final state of a deployment along with its captured output.
+    pub fn mark_finished(
+        &self,
+        id: i64,
+        state: JobState,
+        stdout: Option<&str>,
+        stderr: Option<&str>,
+    ) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE deployments
+             SET state = ?1, finished_at = datetime('now'), stdout = ?2, stderr = ?3
+             WHERE id = ?4",
+            params![state.as_str(), stdout, stderr, id],
+        )?;
+        Ok(())
+    }
+
+    /// Returns all recorded deployments, most recent first.
+    pub fn all(&self) -> rusqlite::Result<Vec<Deployment>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM deployments ORDER BY id DESC")?;
+        let rows = stmt.query_map([], row_to_deployment)?;
+        rows.collect()
+    }
+
+    /// Returns a single deployment by id, if it exists.
+    pub fn by_id(&self, id: i64) -> rusqlite::Result<Option<Deployment>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT * FROM deployments WHERE id = ?1",
+            params![id],
+            row_to_deployment,
+        )
+        .optional()
+    }
+}
@@ -0,0 +1,13 @@
+//! Paths and directory setup for the deployment log files the worker streams.
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// Creates the artifacts directory if it doesn't already exist.
+pub fn reserve_artifacts_dir(dir: &Utf8Path) -> std::io::Result<()> {
+    std::fs::create_dir_all(dir)
+}
+
+/// Path to the log file a given deployment's output is teed to.
+pub fn log_path(artifacts_dir: &Utf8Path, deployment_id: i64) -> Utf8PathBuf {
+    artifacts_dir.join(format!("{deployment_id}.log"))
+}
@@ -1,28 +1,115 @@
-use std::collections::HashMap;
 use std::env;
-use std::process::Command;
-use std::str;
-use std::thread;
+use std::sync::{Arc, RwLock};
 
+use bytes::Bytes;
 use camino::Utf8PathBuf;
-use dropshot::{endpoint, UntypedBody};
+use dropshot::{endpoint, Path, UntypedBody};
 use dropshot::{
     ApiDescription, ConfigDropshot, ConfigLogging, ConfigLoggingIfExists, ConfigLoggingLevel,
     HttpError, HttpResponseOk, HttpServerStarter, RequestContext,
 };
 use hmac::{Hmac, Mac};
-use http::StatusCode;
+use http::{Response, StatusCode};
+use hyper::Body;
 use lettre::{Message, SendmailTransport, Transport};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use sha2::Sha256;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc;
+
+mod config;
+mod db;
+mod io;
+mod notifier;
+mod worker;
+
+use config::Config;
+use db::{DbCtx, Deployment, JobState};
+use notifier::Notifier;
+use worker::{PendingJob, QUEUE_CAPACITY};
 
 #[macro_use(slog_info)]
 extern crate slog;
 
 type HmacSha256 = Hmac<Sha256>;
 
+/// Decodes a lowercase hex string into bytes, returning `None` if it's malformed.
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Returns true if `body`'s HMAC-SHA256 under any of `secrets` matches `received_hash`. Each
+/// candidate is compared in constant time, so the expected signature can't be recovered byte by
+/// byte from response timing. Secrets are tried in order and checking stops at the first match.
+fn verify_signature(secrets: &[String], body: &[u8], received_hash: &[u8]) -> bool {
+    secrets.iter().any(|secret| {
+        let mut mac = match HmacSha256::new_from_slice(secret.as_bytes()) {
+            Ok(v) => v,
+            Err(_) => return false,
+        };
+        mac.update(body);
+        mac.verify_slice(received_hash).is_ok()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_hex_accepts_valid_fixture() {
+        assert_eq!(decode_hex("48656c6c6f"), Some(b"Hello".to_vec()));
+    }
+
+    #[test]
+    fn decode_hex_rejects_odd_length() {
+        assert_eq!(decode_hex("abc"), None);
+    }
+
+    #[test]
+    fn decode_hex_rejects_non_hex_chars() {
+        assert_eq!(decode_hex("zz"), None);
+    }
+
+    #[test]
+    fn verify_signature_matches_any_configured_secret() {
+        let body = b"payload";
+        let mut mac = HmacSha256::new_from_slice(b"current-secret").unwrap();
+        mac.update(body);
+        let received_hash = mac.finalize().into_bytes().to_vec();
+
+        let secrets = vec!["old-secret".to_string(), "current-secret".to_string()];
+        assert!(verify_signature(&secrets, body, &received_hash));
+    }
+
+    #[test]
+    fn verify_signature_rejects_when_no_secret_matches() {
+        let body = b"payload";
+        let mut mac = HmacSha256::new_from_slice(b"current-secret").unwrap();
+        mac.update(body);
+        let received_hash = mac.finalize().into_bytes().to_vec();
+
+        let secrets = vec!["old-secret".to_string(), "another-secret".to_string()];
+        assert!(!verify_signature(&secrets, body, &received_hash));
+    }
+}
+
 struct ServerContext {
     ansible_path: String,
+    db: Arc<DbCtx>,
+    job_tx: mpsc::Sender<PendingJob>,
+    notifier: Arc<Notifier>,
+    config_path: Utf8PathBuf,
+    config: Arc<RwLock<Config>>,
+    artifacts_dir: Utf8PathBuf,
 }
 
 #[tokio::main]
@@ -46,6 +133,10 @@ async fn main() -> Result<(), String> {
      */
     api.register(post_webhook).unwrap();
     api.register(get_status).unwrap();
+    api.register(get_deployments).unwrap();
+    api.register(get_deployment).unwrap();
+    api.register(get_deployment_log).unwrap();
+    api.register(post_reload).unwrap();
 
     // Create an OpenAPI definition, adding title and version.
     let mut openapi = api.openapi("DVRPC Automated Deployments API", "0.1.0");
@@ -59,8 +150,68 @@ async fn main() -> Result<(), String> {
     let ansible_path =
         env::var("PATH_TO_ANSIBLE_PROJECT").expect("Unable to load ansible path from .env file.");
 
-    // Start the server, passing ansible_path in context so it's available to endpoint.
-    let context = ServerContext { ansible_path };
+    // Get env var for path to the deployment history database, panic if it doesn't exist.
+    // `DbCtx::new` creates the database file and schema if they don't already exist.
+    let db_path = env::var("DEPLOYMENT_DB_PATH")
+        .expect("Unable to load deployment database path from .env file.");
+    let db = Arc::new(
+        DbCtx::new(Utf8PathBuf::from(db_path).as_path())
+            .map_err(|e| format!("failed to open deployment database: {}", e))?,
+    );
+
+    // Deployments are enqueued by the webhook handler and run one at a time by a dedicated
+    // worker task, so two webhooks for the same (or different) repos never run ansible-playbook
+    // concurrently against the same inventory. A bounded channel gives us backpressure: once
+    // it's full the handler responds 503 instead of piling up unbounded work.
+    let (job_tx, job_rx) = mpsc::channel(QUEUE_CAPACITY);
+
+    // Read the token used to post commit statuses back to GitHub, panic if it doesn't exist.
+    let github_api_token =
+        env::var("GITHUB_API_TOKEN").expect("Unable to load GitHub API token from .env file.");
+    let notifier = Arc::new(Notifier::new(github_api_token));
+
+    // Get env var for the directory the worker tees live playbook output into, panic if it
+    // doesn't exist. Created on startup if absent.
+    let artifacts_dir = env::var("ARTIFACTS_DIR")
+        .expect("Unable to load artifacts directory path from .env file.");
+    let artifacts_dir = Utf8PathBuf::from(artifacts_dir);
+    io::reserve_artifacts_dir(&artifacts_dir)
+        .map_err(|e| format!("failed to create artifacts directory: {}", e))?;
+
+    // Publicly reachable base URL for this API, used to link GitHub commit statuses back to
+    // `GET /api/deployments/{id}/log`. Left unset (and the link skipped) if the API isn't
+    // reachable from the internet.
+    let public_base_url = env::var("PUBLIC_BASE_URL").unwrap_or_default();
+
+    tokio::spawn(worker::run(
+        job_rx,
+        db.clone(),
+        notifier.clone(),
+        artifacts_dir.clone(),
+        public_base_url,
+        log.clone(),
+    ));
+
+    // Load the repo -> tag mapping and email receivers from a config file, panic if the env var
+    // isn't set and exit with an error if the file doesn't exist or doesn't parse.
+    // `POST /api/reload` re-reads this same path later, so new repos can be onboarded without a
+    // restart.
+    let config_path =
+        env::var("ADA_CONFIG_FILE").expect("Unable to load config file path from .env file.");
+    let config_path = Utf8PathBuf::from(config_path);
+    let config = Config::load(&config_path)?;
+
+    // Start the server, passing ansible_path, the db, the job queue, the notifier, the config,
+    // and the artifacts dir in context so they're available to endpoints.
+    let context = ServerContext {
+        ansible_path,
+        db,
+        job_tx,
+        notifier,
+        config_path,
+        config: Arc::new(RwLock::new(config)),
+        artifacts_dir,
+    };
     let server = HttpServerStarter::new(
         &ConfigDropshot {
             bind_address: "127.0.0.1:7878".parse().unwrap(),
@@ -77,13 +228,182 @@ async fn main() -> Result<(), String> {
     server.await
 }
 
+#[derive(Serialize, JsonSchema)]
+struct Status {
+    status: String,
+    /// Number of deployments currently waiting to be run by the worker.
+    queue_depth: usize,
+}
+
 /// Endpoint for uptime monitoring
 #[endpoint {
     method = GET,
     path = "/api/status"
 }]
-async fn get_status(_: RequestContext<ServerContext>) -> Result<HttpResponseOk<String>, HttpError> {
-    Ok(HttpResponseOk("ok".to_string()))
+async fn get_status(
+    rqctx: RequestContext<ServerContext>,
+) -> Result<HttpResponseOk<Status>, HttpError> {
+    let context = rqctx.context();
+    let queue_depth = QUEUE_CAPACITY - context.job_tx.capacity();
+    Ok(HttpResponseOk(Status {
+        status: "ok".to_string(),
+        queue_depth,
+    }))
+}
+
+/// Re-read the config file from disk, picking up newly added repos or changed receivers without
+/// restarting the server
+#[endpoint {
+    method = POST,
+    path = "/api/reload"
+}]
+async fn post_reload(
+    rqctx: RequestContext<ServerContext>,
+) -> Result<HttpResponseOk<String>, HttpError> {
+    let context = rqctx.context();
+    let config = Config::load(&context.config_path).map_err(|e| HttpError {
+        status_code: StatusCode::INTERNAL_SERVER_ERROR,
+        error_code: None,
+        external_message: "Unable to reload config file.".to_string(),
+        internal_message: e,
+    })?;
+    *context.config.write().unwrap() = config;
+    Ok(HttpResponseOk("Config reloaded.".to_string()))
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct DeploymentPathParams {
+    id: i64,
+}
+
+/// List past deployment attempts, most recent first
+#[endpoint {
+    method = GET,
+    path = "/api/deployments"
+}]
+async fn get_deployments(
+    rqctx: RequestContext<ServerContext>,
+) -> Result<HttpResponseOk<Vec<Deployment>>, HttpError> {
+    let context = rqctx.context();
+    let deployments = context.db.all().map_err(|e| HttpError {
+        status_code: StatusCode::INTERNAL_SERVER_ERROR,
+        error_code: None,
+        external_message: "Unable to read deployment history.".to_string(),
+        internal_message: e.to_string(),
+    })?;
+    Ok(HttpResponseOk(deployments))
+}
+
+/// Look up a single deployment attempt by id
+#[endpoint {
+    method = GET,
+    path = "/api/deployments/{id}"
+}]
+async fn get_deployment(
+    rqctx: RequestContext<ServerContext>,
+    path_params: Path<DeploymentPathParams>,
+) -> Result<HttpResponseOk<Deployment>, HttpError> {
+    let context = rqctx.context();
+    let id = path_params.into_inner().id;
+    let deployment = context.db.by_id(id).map_err(|e| HttpError {
+        status_code: StatusCode::INTERNAL_SERVER_ERROR,
+        error_code: None,
+        external_message: "Unable to read deployment history.".to_string(),
+        internal_message: e.to_string(),
+    })?;
+    match deployment {
+        Some(v) => Ok(HttpResponseOk(v)),
+        None => Err(HttpError {
+            status_code: StatusCode::NOT_FOUND,
+            error_code: None,
+            external_message: format!("No deployment found with id {id}."),
+            internal_message: format!("No deployment found with id {id}."),
+        }),
+    }
+}
+
+/// Stream a deployment's log file, tailing it while the job is still running
+#[endpoint {
+    method = GET,
+    path = "/api/deployments/{id}/log"
+}]
+async fn get_deployment_log(
+    rqctx: RequestContext<ServerContext>,
+    path_params: Path<DeploymentPathParams>,
+) -> Result<Response<Body>, HttpError> {
+    let context = rqctx.context();
+    let id = path_params.into_inner().id;
+
+    let deployment = context.db.by_id(id).map_err(|e| HttpError {
+        status_code: StatusCode::INTERNAL_SERVER_ERROR,
+        error_code: None,
+        external_message: "Unable to read deployment history.".to_string(),
+        internal_message: e.to_string(),
+    })?;
+    let deployment = match deployment {
+        Some(v) => v,
+        None => {
+            return Err(HttpError {
+                status_code: StatusCode::NOT_FOUND,
+                error_code: None,
+                external_message: format!("No deployment found with id {id}."),
+                internal_message: format!("No deployment found with id {id}."),
+            });
+        }
+    };
+
+    let log_path = io::log_path(&context.artifacts_dir, id);
+    let db = context.db.clone();
+
+    // While the job is still pending/running, keep polling the file for new bytes instead of
+    // closing the response once we hit EOF, so `curl`-ing this endpoint behaves like `tail -f`.
+    // The log file itself isn't created until the worker dequeues the job, so a deployment that's
+    // still waiting in line has to be waited on the same way, rather than failing on first open.
+    let stream = async_stream::stream! {
+        let mut state = deployment.state;
+        let mut file = loop {
+            match tokio::fs::File::open(&log_path).await {
+                Ok(f) => break f,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound
+                    && matches!(state, JobState::Pending | JobState::Running) =>
+                {
+                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                    if let Ok(Some(d)) = db.by_id(id) {
+                        state = d.state;
+                    }
+                }
+                Err(e) => {
+                    yield Err(e);
+                    return;
+                }
+            }
+        };
+        let mut buf = [0u8; 8192];
+        loop {
+            match file.read(&mut buf).await {
+                Ok(0) => {
+                    if !matches!(state, JobState::Pending | JobState::Running) {
+                        break;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                    if let Ok(Some(d)) = db.by_id(id) {
+                        state = d.state;
+                    }
+                }
+                Ok(n) => yield Ok(Bytes::copy_from_slice(&buf[..n])),
+                Err(e) => {
+                    yield Err(e);
+                    break;
+                }
+            }
+        }
+    };
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .body(Body::wrap_stream(stream))
+        .unwrap())
 }
 
 /// Handle webhooks for potential automated deployment
@@ -95,27 +415,10 @@ async fn post_webhook(
     rqctx: RequestContext<ServerContext>,
     body: UntypedBody,
 ) -> Result<HttpResponseOk<String>, HttpError> {
-    let mut tag_map = HashMap::from([
-        ("dvrpc/crash-api", "crash"),
-        ("dvrpc/oced-econ-data", "econ_data"),
-        ("dvrpc/low-stress-bike-routing", "low_stress_bike_routing"),
-        ("dvrpc/lps-api", "lspv2"),
-        ("dvrpc/sidewalk-priorities-api", "mcosp"),
-        ("dvrpc/rtsp-api", "rtsp"),
-        ("dvrpc/tp-updates", "tp_updates"),
-        ("dvrpc/cjtf", "cjtf"),
-        ("dvrpc/regional-housing", "housing_submarkets"),
-        ("dvrpc/link", "link_fe"),
-        ("dvrpc/link-api", "link_api"),
-        ("dvrpc/project-intake-api", "project_intake_api"),
-        ("dvrpc/test", "test_app"),
-        ("dvrpc/tip-remix", "tip_25"),
-    ]);
-
     // Get path and log from context.
     let context = rqctx.context();
     let ansible_path = context.ansible_path.clone().to_string();
-    let log = rqctx.log;
+    let log = rqctx.log.clone();
 
     // Get required header
     let headers = rqctx.request.headers();
@@ -136,52 +439,21 @@ async fn post_webhook(
             internal_message: e.to_string(),
         }),
     }?;
-
-    // Get the secret from .env.
-    let secret = match dotenvy::dotenv() {
-        Ok(_) => match env::var("GITHUB_TOKEN") {
-            Ok(v) => Ok(v),
-            Err(e) => Err(HttpError {
-                status_code: StatusCode::INTERNAL_SERVER_ERROR,
-                error_code: None,
-                external_message: "Unable to verify token.".to_string(),
-                internal_message: e.to_string(),
-            }),
-        },
-        Err(e) => Err(HttpError {
-            status_code: StatusCode::INTERNAL_SERVER_ERROR,
-            error_code: None,
-            external_message: "Unable to verify token.".to_string(),
-            internal_message: e.to_string(),
-        }),
-    }?;
-
-    // Compute the hash from our secret and the received body, compare with signature in header.
-    let mut mac = match HmacSha256::new_from_slice(secret.as_bytes()) {
-        Ok(v) => v,
-        Err(e) => {
+    let received_hash = match decode_hex(received_hash) {
+        Some(v) => v,
+        None => {
             return Err(HttpError {
-                status_code: StatusCode::INTERNAL_SERVER_ERROR,
+                status_code: StatusCode::FORBIDDEN,
                 error_code: None,
-                external_message: "Unable to verify token.".to_string(),
-                internal_message: e.to_string(),
-            })
+                external_message: "Invalid token.".to_string(),
+                internal_message: "Signature header was not valid hex".to_string(),
+            });
         }
     };
-    mac.update(body.as_bytes());
-    let computed_hash = format!("{:x}", mac.finalize().into_bytes());
-
-    if computed_hash != received_hash {
-        return Err(HttpError {
-            status_code: StatusCode::FORBIDDEN,
-            error_code: None,
-            external_message: "Invalid token.".to_string(),
-            internal_message: "Mismatched hashes".to_string(),
-        });
-    }
 
     // Get body to extract information from.
-    let body = match serde_json::from_slice::<Value>(body.as_bytes()) {
+    let body_bytes = body.as_bytes();
+    let body = match serde_json::from_slice::<Value>(body_bytes) {
         Ok(v) => v,
         Err(_) => {
             return Err(HttpError {
@@ -193,6 +465,45 @@ async fn post_webhook(
         }
     };
 
+    // Each repo has its own webhook secret(s) (more than one during rotation), looked up by the
+    // repository's full name so that compromising or rotating one repo's secret doesn't affect
+    // any other.
+    let name = match body.get("repository") {
+        Some(repo) => repo["full_name"].as_str().unwrap_or_default().to_string(),
+        None => {
+            return Err(HttpError {
+                status_code: StatusCode::BAD_REQUEST,
+                error_code: None,
+                external_message: "Unable to get repository field from webhook.".to_string(),
+                internal_message: "Unable to get repository field from webhook.".to_string(),
+            });
+        }
+    };
+
+    // Look this repo up once, before checking the signature, so an unconfigured repo gets a
+    // distinct 400 instead of being indistinguishable from a bad signature.
+    let repo_config = match context.config.read().unwrap().repo(&name) {
+        None => {
+            return Err(HttpError {
+                status_code: StatusCode::BAD_REQUEST,
+                error_code: None,
+                external_message: format!("{} is not set up for automated deployment.", &name),
+                internal_message: format!("{} is not set up for automated deployment.", &name),
+            });
+        }
+        Some(v) => v.clone(),
+    };
+
+    if !verify_signature(&repo_config.webhook_secrets, body_bytes, &received_hash) {
+        return Err(HttpError {
+            status_code: StatusCode::FORBIDDEN,
+            error_code: None,
+            external_message: "Invalid token.".to_string(),
+            internal_message: "No configured secret for this repo matched the signature"
+                .to_string(),
+        });
+    }
+
     // The webhook should be configured to send on pull request events only. However, there is no
     // "successful pull request" event - we have to determine that from the request body.
     let action = match body.get("action") {
@@ -207,8 +518,8 @@ async fn post_webhook(
         }
     };
 
-    let merged = match body.get("pull_request") {
-        Some(pull_request) => pull_request["merged"].clone(),
+    let pull_request = match body.get("pull_request") {
+        Some(v) => v,
         None => {
             return Err(HttpError {
                 status_code: StatusCode::BAD_REQUEST,
@@ -220,21 +531,17 @@ async fn post_webhook(
             });
         }
     };
-
-    // Determine what app/API this is for.
-    let name = match body.get("repository") {
-        // Value.as_str() strips double quotes, but we also need it to be owned, so also
-        // use to_string()
-        Some(repo) => repo["full_name"].as_str().unwrap().to_string(),
-        None => {
-            return Err(HttpError {
-                status_code: StatusCode::BAD_REQUEST,
-                error_code: None,
-                external_message: "Unable to get repository field from webhook.".to_string(),
-                internal_message: "Unable to get repository field from webhook.".to_string(),
-            });
-        }
-    };
+    let merged = pull_request["merged"].clone();
+    let sha = pull_request["merge_commit_sha"]
+        .as_str()
+        .unwrap_or("unknown")
+        .to_string();
+
+    // Where to post commit statuses for this repo, if anywhere.
+    let statuses_url = body["repository"]["statuses_url"]
+        .as_str()
+        .unwrap_or_default()
+        .replace("{sha}", &sha);
 
     // If action was not "closed", just log and return early.
     if action != "closed" {
@@ -245,11 +552,13 @@ async fn post_webhook(
     // If merged is false, log, email, and return early.
     if merged == false {
         slog_info!(log, "Pull request status"; "merged" => "false");
-        // Email the results to addresses in .env file. The message is built in separate chunks
-        // b/c the number of addresses is unknown, otherwise it could all be chained at once.
-        let receivers =
-            env::var("EMAIL_RECEIVERS").expect("Unable to load email addreses from .env file");
-        let receivers = receivers.split(',').collect::<Vec<_>>();
+        // Email the results to the configured receivers. The message is built in separate
+        // chunks b/c the number of addresses is unknown, otherwise it could all be chained at
+        // once.
+        let receivers = repo_config
+            .receivers
+            .clone()
+            .unwrap_or_else(|| context.config.read().unwrap().receivers.clone());
 
         let mut email = Message::builder().from(
             "Controller <root@controller.cloud.dvrpc.org>"
@@ -258,7 +567,12 @@ async fn post_webhook(
         );
 
         for receiver in receivers.iter() {
-            email = email.to(receiver.parse().unwrap());
+            match receiver.parse() {
+                Ok(v) => email = email.to(v),
+                Err(e) => {
+                    slog_info!(log, "Skipping invalid receiver address"; "receiver" => receiver, "error" => e.to_string());
+                }
+            }
         }
 
         let email = email
@@ -276,92 +590,64 @@ async fn post_webhook(
         ));
     }
 
-    // Get corresponding tag to use in Ansible playbook.
-    let tag = match tag_map.remove(name.as_str()) {
-        None => {
+    // The repo's deployment settings (tag, inventory, receivers, etc.) were already looked up
+    // above, alongside its webhook secrets.
+    let tag = repo_config.tag.clone();
+    let inventory = repo_config.inventory.clone();
+    let remote_user = repo_config.remote_user.clone();
+    let extra_args = repo_config.extra_args.clone();
+    let receivers = repo_config
+        .receivers
+        .clone()
+        .unwrap_or_else(|| context.config.read().unwrap().receivers.clone());
+
+    // Reserve a queue slot before recording anything: if the queue is full we want to 503
+    // without leaving a deployment row stuck in `pending` forever, since the worker will never
+    // see it.
+    let permit = match context.job_tx.try_reserve() {
+        Ok(v) => v,
+        Err(e) => {
+            slog_info!(log, "Deployment queue is full"; "error" => e.to_string());
             return Err(HttpError {
-                status_code: StatusCode::BAD_REQUEST,
+                status_code: StatusCode::SERVICE_UNAVAILABLE,
                 error_code: None,
-                external_message: format!("{} is not set up for automated deployment.", &name),
-                internal_message: format!("{} is not set up for automated deployment.", &name),
+                external_message: "Deployment queue is full, try again later.".to_string(),
+                internal_message: e.to_string(),
             });
         }
-        Some(v) => v,
     };
 
+    // Now that the slot is secured, record a pending row so the history shows the deployment was
+    // attempted even if the process never reports back.
+    let deployment_id = context
+        .db
+        .insert_pending(&name, &tag, &sha)
+        .map_err(|e| HttpError {
+            status_code: StatusCode::INTERNAL_SERVER_ERROR,
+            error_code: None,
+            external_message: "Unable to record deployment.".to_string(),
+            internal_message: e.to_string(),
+        })?;
+
     // Github's webhooks have a 10-second timeout
     // (see <https://docs.github.com/en/webhooks/testing-and-troubleshooting-webhooks/troubleshooting-webhooks#timed-out>)
-    // and since Ansible playbooks usually take much longer than this to run, we put it in a
-    // thread so it can be done in the background and we can send a response to the webhook.
-    // This means we must log the result separately from the response.
-
-    thread::spawn(move || {
-        let output = Command::new("ansible-playbook")
-            .current_dir(ansible_path)
-            .args([
-                "playbook.yml",
-                "-i",
-                "inventories/from_controller.yaml",
-                "-u",
-                "controller",
-                "--tags",
-                tag,
-            ])
-            .output();
-
-        // Collect result of running command.
-        let (status, stdout, stderr) = match output {
-            Ok(v) => {
-                let status = match v.status.success() {
-                    true => "success".to_string(),
-                    false => "failure".to_string(),
-                };
-                (status, Some(v.stdout), Some(v.stderr))
-            }
-            Err(e) => (e.to_string(), None, None),
-        };
-        slog_info!(log, "Ansible command completed"; "status" => status.clone());
-
-        let mut email_body = format!("Attempt to redeploy {name}: {status}");
-
-        if let Some(v) = stdout {
-            email_body.push_str(str::from_utf8(&v).unwrap())
-        }
-
-        if let Some(v) = stderr {
-            email_body.push_str(str::from_utf8(&v).unwrap())
-        }
-
-        // Email the results to addresses in .env file. The message is built in separate chunks
-        // b/c the number of addresses is unknown, otherwise it could all be chained at once.
-        let receivers =
-            env::var("EMAIL_RECEIVERS").expect("Unable to load email addreses from .env file");
-        let receivers = receivers.split(',').collect::<Vec<_>>();
-
-        let mut email = Message::builder().from(
-            "Controller <root@controller.cloud.dvrpc.org>"
-                .parse()
-                .unwrap(),
-        );
-
-        for receiver in receivers.iter() {
-            email = email.to(receiver.parse().unwrap());
-        }
-
-        let email = email
-            .subject("Result from automated deployment API")
-            .body(email_body)
-            .unwrap();
-
-        // Use local sendmail program to send email.
-        let sender = SendmailTransport::new();
-        let _ = sender.send(&email);
-    });
+    // and since Ansible playbooks usually take much longer than this to run, we hand it off to
+    // the background worker and respond to the webhook right away.
+    let job = PendingJob {
+        deployment_id,
+        repo: name,
+        tag,
+        inventory,
+        remote_user,
+        extra_args,
+        ansible_path,
+        receivers: receivers.clone(),
+        statuses_url,
+    };
+    permit.send(job);
 
-    let completed_response = format!(
-        "Redeployment will be attempted - results will be emailed to {:?}.",
-        env::var("EMAIL_RECEIVERS")
-    );
+    let completed_response =
+        format!("Redeployment will be attempted - results will be emailed to {receivers:?}.");
 
     Ok(HttpResponseOk(completed_response))
 }
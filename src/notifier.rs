@@ -0,0 +1,75 @@
+//! Reports deployment results back to GitHub via the commit statuses API.
+
+use serde::Serialize;
+
+/// State of a GitHub commit status, per
+/// <https://docs.github.com/en/rest/commits/statuses>.
+#[derive(Debug, Clone, Copy)]
+pub enum StatusState {
+    Pending,
+    Success,
+    Failure,
+}
+
+impl StatusState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            StatusState::Pending => "pending",
+            StatusState::Success => "success",
+            StatusState::Failure => "failure",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct StatusBody<'a> {
+    state: &'a str,
+    description: &'a str,
+    context: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_url: Option<&'a str>,
+}
+
+/// Posts commit statuses to GitHub on behalf of the deployment worker.
+pub struct Notifier {
+    client: reqwest::Client,
+    token: String,
+}
+
+impl Notifier {
+    pub fn new(token: String) -> Self {
+        Notifier {
+            client: reqwest::Client::new(),
+            token,
+        }
+    }
+
+    /// Sets the commit status at `statuses_url` (a repo's `statuses_url` template, with `{sha}`
+    /// already substituted) to `state`.
+    pub async fn set_status(
+        &self,
+        statuses_url: &str,
+        state: StatusState,
+        description: &str,
+        target_url: Option<&str>,
+    ) -> reqwest::Result<()> {
+        let body = StatusBody {
+            state: state.as_str(),
+            description,
+            context: "automated-deployments",
+            target_url,
+        };
+
+        self.client
+            .post(statuses_url)
+            .bearer_auth(&self.token)
+            .header("User-Agent", "dvrpc-automated-deployments-api")
+            .header("Accept", "application/vnd.github+json")
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+}
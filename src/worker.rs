@@ -0,0 +1,229 @@
+//! Background worker that drains the deployment queue and runs playbooks one at a time.
+
+use std::process::Stdio;
+use std::sync::Arc;
+
+use camino::Utf8PathBuf;
+use lettre::{Message, SendmailTransport, Transport};
+use slog::Logger;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+
+use crate::db::{DbCtx, JobState};
+use crate::io::log_path;
+use crate::notifier::{Notifier, StatusState};
+
+/// A deployment waiting to be run by the worker.
+pub struct PendingJob {
+    pub deployment_id: i64,
+    pub repo: String,
+    pub tag: String,
+    pub inventory: String,
+    pub remote_user: String,
+    pub extra_args: Vec<String>,
+    pub ansible_path: String,
+    pub receivers: Vec<String>,
+    /// The repository's `statuses_url`, with `{sha}` already substituted for the merge commit.
+    /// Empty if the webhook body didn't include one, in which case status reporting is skipped.
+    pub statuses_url: String,
+}
+
+/// How many jobs may sit in the queue before the webhook handler starts rejecting new ones with
+/// a 503. This is deliberately small: a deep queue just means operators wait longer to find out
+/// a deploy failed.
+pub const QUEUE_CAPACITY: usize = 16;
+
+/// Drains `rx` and runs one playbook at a time, recording the result in `db`, reporting it to
+/// GitHub via `notifier`, and emailing it to `receivers`. Runs until the channel is closed (i.e.
+/// the server is shutting down).
+pub async fn run(
+    mut rx: tokio::sync::mpsc::Receiver<PendingJob>,
+    db: Arc<DbCtx>,
+    notifier: Arc<Notifier>,
+    artifacts_dir: Utf8PathBuf,
+    public_base_url: String,
+    log: Logger,
+) {
+    while let Some(job) = rx.recv().await {
+        run_job(job, &db, &notifier, &artifacts_dir, &public_base_url, &log).await;
+    }
+}
+
+/// Tees one of the child's output streams, line by line, into the deployment's log file, and
+/// returns the full captured text once the stream closes.
+///
+/// Keeps draining `reader` even if the log file can't be opened or written to, so a filesystem
+/// problem never leaves the child's pipe buffer full and `ansible-playbook` blocked on write
+/// forever - that would wedge this job (and, since the worker runs one job at a time, every job
+/// behind it) with no way to recover short of restarting the process.
+async fn tee_stream<R>(reader: R, log_file: Utf8PathBuf, prefix: &'static str) -> String
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut file = OpenOptions::new().append(true).open(&log_file).await.ok();
+    let mut lines = BufReader::new(reader).lines();
+    let mut captured = String::new();
+
+    loop {
+        let line = match lines.next_line().await {
+            Ok(Some(v)) => v,
+            Ok(None) | Err(_) => break,
+        };
+        if let Some(f) = file.as_mut() {
+            if f.write_all(format!("[{prefix}] {line}\n").as_bytes())
+                .await
+                .is_err()
+            {
+                file = None;
+            }
+        }
+        captured.push_str(&line);
+        captured.push('\n');
+    }
+    captured
+}
+
+async fn run_job(
+    job: PendingJob,
+    db: &Arc<DbCtx>,
+    notifier: &Arc<Notifier>,
+    artifacts_dir: &Utf8PathBuf,
+    public_base_url: &str,
+    log: &Logger,
+) {
+    // Link the commit status back to this deployment's log, if an operator-facing base URL is
+    // configured. Left out (rather than pointing at an empty/garbage URL) when it isn't.
+    let target_url = if public_base_url.is_empty() {
+        None
+    } else {
+        Some(format!(
+            "{public_base_url}/api/deployments/{}/log",
+            job.deployment_id
+        ))
+    };
+
+    if let Err(e) = db.mark_running(job.deployment_id) {
+        slog_info!(log, "Unable to mark deployment as running"; "error" => e.to_string());
+    }
+    if !job.statuses_url.is_empty() {
+        if let Err(e) = notifier
+            .set_status(
+                &job.statuses_url,
+                StatusState::Pending,
+                "Deployment started",
+                target_url.as_deref(),
+            )
+            .await
+        {
+            slog_info!(log, "Unable to post pending status to GitHub"; "error" => e.to_string());
+        }
+    }
+
+    let log_file = log_path(artifacts_dir, job.deployment_id);
+    if let Err(e) = std::fs::File::create(&log_file) {
+        slog_info!(log, "Unable to create deployment log file"; "error" => e.to_string());
+    }
+
+    let child = Command::new("ansible-playbook")
+        .current_dir(&job.ansible_path)
+        .args([
+            "playbook.yml",
+            "-i",
+            &job.inventory,
+            "-u",
+            &job.remote_user,
+            "--tags",
+            &job.tag,
+        ])
+        .args(&job.extra_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn();
+
+    let (status, job_state, stdout_str, stderr_str) = match child {
+        Ok(mut child) => {
+            let stdout = child.stdout.take().expect("child stdout was piped");
+            let stderr = child.stderr.take().expect("child stderr was piped");
+
+            // Tee both streams to the log file concurrently as they arrive, so operators can
+            // follow along with `GET /api/deployments/{id}/log` while the playbook is still
+            // running, instead of waiting for it to finish.
+            let (stdout_str, stderr_str, wait_result) = tokio::join!(
+                tee_stream(stdout, log_file.clone(), "stdout"),
+                tee_stream(stderr, log_file.clone(), "stderr"),
+                child.wait(),
+            );
+
+            let (status, job_state) = match wait_result {
+                Ok(exit_status) if exit_status.success() => {
+                    ("success".to_string(), JobState::Success)
+                }
+                Ok(_) => ("failure".to_string(), JobState::Failure),
+                Err(e) => (e.to_string(), JobState::Failure),
+            };
+            (status, job_state, Some(stdout_str), Some(stderr_str))
+        }
+        Err(e) => (e.to_string(), JobState::Failure, None, None),
+    };
+    slog_info!(log, "Ansible command completed"; "status" => status.clone());
+
+    if let Err(e) = db.mark_finished(
+        job.deployment_id,
+        job_state,
+        stdout_str.as_deref(),
+        stderr_str.as_deref(),
+    ) {
+        slog_info!(log, "Unable to record deployment result"; "error" => e.to_string());
+    }
+    if !job.statuses_url.is_empty() {
+        let notify_state = match job_state {
+            JobState::Success => StatusState::Success,
+            _ => StatusState::Failure,
+        };
+        let description = format!("Deployment {status}");
+        if let Err(e) = notifier
+            .set_status(
+                &job.statuses_url,
+                notify_state,
+                &description,
+                target_url.as_deref(),
+            )
+            .await
+        {
+            slog_info!(log, "Unable to post final status to GitHub"; "error" => e.to_string());
+        }
+    }
+
+    let mut email_body = format!("Attempt to redeploy {}: {status}", job.repo);
+    if let Some(v) = stdout_str {
+        email_body.push_str(&v)
+    }
+    if let Some(v) = stderr_str {
+        email_body.push_str(&v)
+    }
+
+    // Email the results to the configured receivers. The message is built in separate chunks
+    // b/c the number of addresses is unknown, otherwise it could all be chained at once.
+    let mut email = Message::builder().from(
+        "Controller <root@controller.cloud.dvrpc.org>"
+            .parse()
+            .unwrap(),
+    );
+    for receiver in &job.receivers {
+        match receiver.parse() {
+            Ok(v) => email = email.to(v),
+            Err(e) => {
+                slog_info!(log, "Skipping invalid receiver address"; "receiver" => receiver, "error" => e.to_string());
+            }
+        }
+    }
+    let email = email
+        .subject("Result from automated deployment API")
+        .body(email_body)
+        .unwrap();
+
+    // Use local sendmail program to send email.
+    let sender = SendmailTransport::new();
+    let _ = sender.send(&email);
+}